@@ -7,7 +7,10 @@ use crate::{
 };
 
 use anyhow::{anyhow, Result};
-use async_compression::tokio::write::{BrotliDecoder, DeflateDecoder, GzipDecoder};
+use async_compression::tokio::write::{
+    BrotliDecoder, BrotliEncoder, DeflateDecoder, DeflateEncoder, GzipDecoder, GzipEncoder,
+    ZstdDecoder, ZstdEncoder,
+};
 use bytes::Bytes;
 use futures_util::{stream, StreamExt, TryStreamExt};
 use http::{
@@ -15,10 +18,10 @@ use http::{
     uri::{Authority, Scheme},
     HeaderValue,
 };
-use http_body_util::{combinators::BoxBody, BodyExt, Full, StreamBody};
+use http_body_util::{combinators::BoxBody, BodyExt, BodyStream, Full, StreamBody};
 use hyper::{
     body::{Frame, Incoming},
-    header::{CONTENT_ENCODING, HOST},
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, HOST},
     service::service_fn,
     Method, StatusCode, Uri,
 };
@@ -28,7 +31,13 @@ use hyper_util::{
     rt::{TokioExecutor, TokioIo},
 };
 use serde::Serialize;
-use std::sync::{atomic::AtomicBool, Arc};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
@@ -50,6 +59,20 @@ pub(crate) struct Server {
     pub(crate) filters: Vec<Filter>,
     pub(crate) mime_filters: Vec<String>,
     pub(crate) state: State,
+    /// Maximum number of body bytes the recorder retains; the proxy keeps
+    /// streaming past this point but the recorded copy is truncated.
+    pub(crate) max_record_body_size: usize,
+    /// Forward a PROXY-protocol header to raw upstream connections, preserving
+    /// the downstream client address. Only a header parsed from the downstream
+    /// connection is replayed; none is synthesized when the downstream sent none.
+    pub(crate) send_proxy_header: bool,
+    /// Scrapeable counters surfaced at `/__proxyfor__/metrics`.
+    pub(crate) metrics: Arc<Metrics>,
+    /// Compress web UI responses against the client's `Accept-Encoding`.
+    pub(crate) enable_compression: bool,
+    /// MIME patterns (e.g. `text/*`, `application/json`) eligible for
+    /// outbound compression.
+    pub(crate) compress_mime_types: Vec<String>,
     #[allow(unused)]
     pub(crate) running: Arc<AtomicBool>,
 }
@@ -99,7 +122,10 @@ impl Server {
             } else if path == "/subscribe" {
                 self.handle_subscribe_traffic(&mut res).await
             } else if path == "/traffics" {
-                self.handle_list_traffis(&mut res).await
+                let query = req_uri.split_once('?').map(|(_, q)| q).unwrap_or("");
+                self.handle_list_traffis(&mut res, query).await
+            } else if path == "/metrics" {
+                self.handle_metrics(&mut res).await
             } else if let Some(id) = path.strip_prefix("/traffic/") {
                 self.handle_traffic_info(&mut res, id).await
             } else {
@@ -110,9 +136,20 @@ impl Server {
                 *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
                 set_res_body(&mut res, err.to_string());
             }
+            // Never compress the open-ended `/subscribe` SSE stream: buffering it
+            // to compress would never terminate and would hang the connection.
+            if self.enable_compression && path != "/subscribe" {
+                let accept_encoding = req_headers
+                    .get(ACCEPT_ENCODING)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("");
+                self.compress_response(&mut res, accept_encoding).await;
+            }
             return Ok(res);
         }
 
+        self.metrics.on_request_received(method.as_str());
+
         let mut recorder = Recorder::new(&req_uri, method.as_str());
 
         recorder.control_dump(is_match_title(&self.filters, &format!("{method} {url}")));
@@ -124,15 +161,22 @@ impl Server {
 
         recorder.set_req_headers(&req_headers);
 
-        let req_body = match req.collect().await {
-            Ok(v) => v.to_bytes(),
-            Err(err) => {
-                self.internal_server_error(&mut res, err, recorder);
-                return Ok(res);
-            }
-        };
-
-        recorder.set_req_body(req_body.clone());
+        // Stream the request body straight to the upstream, teeing each chunk
+        // through a channel rather than buffering the whole upload in memory.
+        // The channel closes when the request body stream reaches EOF, which for
+        // HTTP/2 / full-duplex uploads can be well after the response head.
+        let max_record_body_size = self.max_record_body_size;
+        let req_metrics = self.metrics.clone();
+        let (req_tx, req_rx) = tokio::sync::mpsc::unbounded_channel::<Bytes>();
+        let req_stream = BodyStream::new(req.into_body())
+            .map_ok(move |frame| {
+                if let Some(data) = frame.data_ref() {
+                    req_metrics.add_bytes_in(data.len() as u64);
+                    let _ = req_tx.send(data.clone());
+                }
+                frame
+            })
+            .map_err(|err| anyhow!("{err}"));
 
         let mut builder = hyper::Request::builder().uri(&url).method(method.clone());
         for (key, value) in req_headers.iter() {
@@ -142,7 +186,7 @@ impl Server {
             builder = builder.header(key.clone(), value.clone());
         }
 
-        let proxy_req = match builder.body(Full::new(req_body)) {
+        let proxy_req = match builder.body(BodyExt::boxed(StreamBody::new(req_stream))) {
             Ok(v) => v,
             Err(err) => {
                 self.internal_server_error(&mut res, err, recorder);
@@ -158,6 +202,7 @@ impl Server {
                         .with_webpki_roots()
                         .https_only()
                         .enable_http1()
+                        .enable_http2()
                         .build(),
                 )
                 .request(proxy_req)
@@ -176,6 +221,8 @@ impl Server {
         let proxy_res_status = proxy_res.status();
         let proxy_res_headers = proxy_res.headers().clone();
 
+        self.metrics.on_response(proxy_res_status);
+
         if let Some(header_value) = proxy_res_headers
             .get(CONTENT_TYPE)
             .and_then(|v| v.to_str().ok())
@@ -194,30 +241,60 @@ impl Server {
             res.headers_mut().insert(key.clone(), value.clone());
         }
 
-        let proxy_res_body = match proxy_res.collect().await {
-            Ok(v) => v.to_bytes(),
-            Err(err) => {
-                self.internal_server_error(&mut res, err, recorder);
-                return Ok(res);
-            }
-        };
-
+        // Record the negotiated protocol version (h1/h2) alongside the response
+        // headers so the web UI can surface it, without leaking the synthetic
+        // header onto the real client response.
+        let mut recorded_headers = proxy_res_headers.clone();
+        if let Ok(version) = HeaderValue::from_str(&format!("{:?}", proxy_res.version())) {
+            recorded_headers.insert("proxyfor-http-version", version);
+        }
         recorder
             .set_res_status(proxy_res_status)
-            .set_res_headers(&proxy_res_headers);
+            .set_res_headers(&recorded_headers);
 
-        if !proxy_res_body.is_empty() {
-            let decompress_body = decompress(&proxy_res_body, encoding)
-                .await
-                .unwrap_or_else(|| proxy_res_body.to_vec());
-            recorder.set_res_body(Bytes::from(decompress_body));
-        }
+        // The response headers are through, so count the request as served now;
+        // this keeps metrics accurate for never-ending streams (e.g. SSE) whose
+        // bodies may not reach EOF for a long time.
+        self.metrics.on_request_served();
 
-        self.take_recorder(recorder);
+        // Tee every data chunk into the recorder through a channel drained by a
+        // background task while the body is streamed straight through to the
+        // client. The recorder keeps only a truncated prefix once it exceeds
+        // `max_record_body_size`, but the proxy never stops forwarding. Note the
+        // recorded traffic is only surfaced once the body ends — an open stream
+        // is recorded when it finally closes.
+        let encoding = encoding.to_string();
+        let server = self.clone();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Bytes>();
+        let mut req_rx = req_rx;
+        tokio::spawn(async move {
+            // Wait for the request upload to finish before recording its body;
+            // the channel drains until the request stream is dropped at EOF.
+            let req_body = collect_capped(&mut req_rx, max_record_body_size).await;
+            recorder.set_req_body(Bytes::from(req_body));
 
-        *res.body_mut() = Full::new(proxy_res_body)
-            .map_err(|err| anyhow!("{err}"))
-            .boxed();
+            let body = collect_capped(&mut rx, max_record_body_size).await;
+            if !body.is_empty() {
+                let body = Bytes::from(body);
+                let decompress_body = decompress(&body, &encoding)
+                    .await
+                    .unwrap_or_else(|| body.to_vec());
+                recorder.set_res_body(Bytes::from(decompress_body));
+            }
+            server.take_recorder(recorder);
+        });
+
+        let metrics = self.metrics.clone();
+        let stream = BodyStream::new(proxy_res.into_body())
+            .map_ok(move |frame| {
+                if let Some(data) = frame.data_ref() {
+                    metrics.add_bytes_out(data.len() as u64);
+                    let _ = tx.send(data.clone());
+                }
+                frame
+            })
+            .map_err(|err| anyhow!("{err}"));
+        *res.body_mut() = BodyExt::boxed(StreamBody::new(stream));
 
         Ok(res)
     }
@@ -278,12 +355,42 @@ impl Server {
         Ok(())
     }
 
-    async fn handle_list_traffis(self: &Arc<Self>, res: &mut Response) -> Result<()> {
-        set_res_body(res, serde_json::to_string_pretty(&self.state.list())?);
+    async fn handle_list_traffis(self: &Arc<Self>, res: &mut Response, query: &str) -> Result<()> {
+        let params = TrafficQuery::parse(query);
+
+        // Serialize the heads to JSON values so filtering can inspect fields
+        // without coupling to the concrete head struct.
+        let mut items: Vec<serde_json::Value> = serde_json::to_value(self.state.list())?
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| params.matches(item))
+            .collect();
+
+        if let Some(sort) = &params.sort {
+            items.sort_by(|a, b| {
+                field_str(a, sort)
+                    .unwrap_or_default()
+                    .cmp(&field_str(b, sort).unwrap_or_default())
+            });
+        }
+
+        let total = items.len();
+        let offset = params.offset.unwrap_or(0);
+        let paged: Vec<serde_json::Value> = match params.limit {
+            Some(limit) => items.into_iter().skip(offset).take(limit).collect(),
+            None => items.into_iter().skip(offset).collect(),
+        };
+
+        set_res_body(res, serde_json::to_string_pretty(&paged)?);
         res.headers_mut().insert(
             CONTENT_TYPE,
             HeaderValue::from_static("application/json; charset=UTF-8"),
         );
+        if let Ok(value) = HeaderValue::from_str(&total.to_string()) {
+            res.headers_mut().insert("x-total-count", value);
+        }
         res.headers_mut()
             .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
         Ok(())
@@ -307,6 +414,63 @@ impl Server {
         Ok(())
     }
 
+    /// Compress a web UI response in place, picking the best codec the client
+    /// accepts among br/gzip/deflate/zstd. Skips already-encoded bodies and
+    /// content types outside the configured allow-list.
+    async fn compress_response(self: &Arc<Self>, res: &mut Response, accept_encoding: &str) {
+        if res.headers().contains_key(CONTENT_ENCODING) {
+            return;
+        }
+        let content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        if !is_match_type(&self.compress_mime_types, content_type) {
+            return;
+        }
+        let Some(encoding) = select_encoding(accept_encoding) else {
+            return;
+        };
+
+        let body = match std::mem::replace(res.body_mut(), Full::new(Bytes::new()).map_err(|err| anyhow!("{err}")).boxed())
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return,
+        };
+
+        let compressed = match compress(&body, encoding).await {
+            Ok(compressed) => compressed,
+            Err(_) => {
+                *res.body_mut() = Full::new(body).map_err(|err| anyhow!("{err}")).boxed();
+                return;
+            }
+        };
+
+        let compressed = Bytes::from(compressed);
+        if let Ok(header_value) = HeaderValue::from_str(&compressed.len().to_string()) {
+            res.headers_mut().insert(CONTENT_LENGTH, header_value);
+        }
+        res.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+        *res.body_mut() = Full::new(compressed)
+            .map_err(|err| anyhow!("{err}"))
+            .boxed();
+    }
+
+    async fn handle_metrics(self: &Arc<Self>, res: &mut Response) -> Result<()> {
+        set_res_body(res, self.metrics.render());
+        res.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+        );
+        res.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        Ok(())
+    }
+
     fn handle_connect(
         self: Arc<Self>,
         mut req: Request,
@@ -341,62 +505,52 @@ impl Server {
                         bytes::Bytes::copy_from_slice(buffer[..bytes_read].as_ref()),
                     );
 
-                    if buffer == *b"GET " {
-                        if let Err(e) = self
-                            .serve_connect_stream(upgraded, Scheme::HTTP, authority)
-                            .await
-                        {
-                            recorder.add_error(format!("Websocket connect error: {e}"));
-                        }
-                    } else if buffer[..2] == *b"\x16\x03" {
-                        let server_config = match self.ca.gen_server_config(&authority).await {
-                            Ok(server_config) => server_config,
+                    // A downstream load balancer may prefix the connection with a
+                    // PROXY-protocol header carrying the real client address.
+                    // Strip it off, record the recovered address, then re-sniff
+                    // the real protocol that follows.
+                    if buffer[..4] == *b"PROX" || buffer == *b"\x0D\x0A\x0D\x0A" {
+                        let is_v2 = buffer == *b"\x0D\x0A\x0D\x0A";
+                        let proxy_header = match read_proxy_header(&mut upgraded, is_v2).await {
+                            Ok(header) => header,
                             Err(e) => {
-                                recorder.add_error(format!("Failed to build server config: {e}"));
+                                recorder.add_error(format!("Failed to parse PROXY header: {e}"));
                                 return;
                             }
                         };
-
-                        let stream = match TlsAcceptor::from(server_config).accept(upgraded).await {
-                            Ok(stream) => stream,
+                        let mut buffer = [0; 4];
+                        let bytes_read = match upgraded.read_exact(&mut buffer).await {
+                            Ok(bytes_read) => bytes_read,
                             Err(e) => {
-                                recorder
-                                    .add_error(format!("Failed to establish TLS Connection: {e}"));
+                                recorder.add_error(format!(
+                                    "Failed to read from upgraded connection: {e}"
+                                ));
                                 return;
                             }
                         };
-
-                        if let Err(e) = self
-                            .serve_connect_stream(stream, Scheme::HTTPS, authority)
-                            .await
-                        {
-                            if !e.to_string().starts_with("error shutting down connection") {
-                                recorder.add_error(format!("HTTPS connect error: {e}"));
-                            }
-                        }
+                        let upgraded = Rewind::new_buffered(
+                            upgraded,
+                            bytes::Bytes::copy_from_slice(buffer[..bytes_read].as_ref()),
+                        );
+                        self.dispatch_connect(
+                            upgraded,
+                            buffer,
+                            bytes_read,
+                            authority,
+                            &mut recorder,
+                            Some(proxy_header),
+                        )
+                        .await;
                     } else {
-                        recorder.add_error(format!(
-                            "Unknown protocol, read '{:02X?}' from upgraded connection",
-                            &buffer[..bytes_read]
-                        ));
-
-                        let mut server = match TcpStream::connect(authority.as_str()).await {
-                            Ok(server) => server,
-                            Err(e) => {
-                                recorder
-                                    .add_error(format! {"Failed to connect to {authority}: {e}"});
-                                return;
-                            }
-                        };
-
-                        if let Err(e) =
-                            tokio::io::copy_bidirectional(&mut upgraded, &mut server).await
-                        {
-                            recorder.add_error(format!(
-                                "Failed to tunnel unknown protocol to {}: {}",
-                                authority, e
-                            ));
-                        }
+                        self.dispatch_connect(
+                            upgraded,
+                            buffer,
+                            bytes_read,
+                            authority,
+                            &mut recorder,
+                            None,
+                        )
+                        .await;
                     }
                 }
                 Err(e) => {
@@ -409,11 +563,107 @@ impl Server {
         Ok(Response::default())
     }
 
+    /// Sniff the protocol carried by an upgraded CONNECT stream and route it to
+    /// the MITM HTTPS server, the WebSocket tap, or a raw tunnel. `proxy_header`
+    /// carries an inbound PROXY header (if any) to optionally re-emit upstream.
+    async fn dispatch_connect<I>(
+        self: &Arc<Self>,
+        mut upgraded: I,
+        buffer: [u8; 4],
+        bytes_read: usize,
+        authority: Authority,
+        recorder: &mut ErrorRecorder,
+        proxy_header: Option<ProxyHeader>,
+    ) where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        // The PROXY header (if any) carries the real downstream client address;
+        // surface it on every proxied request as a recorded header.
+        let client_addr: Option<Arc<str>> = proxy_header
+            .as_ref()
+            .and_then(|header| header.client.clone())
+            .map(Arc::from);
+
+        if buffer == *b"GET " {
+            let upgraded = WsTapStream::new(upgraded, self.state.clone());
+            if let Err(e) = self
+                .clone()
+                .serve_connect_stream(upgraded, Scheme::HTTP, authority, client_addr)
+                .await
+            {
+                recorder.add_error(format!("Websocket connect error: {e}"));
+            }
+        } else if buffer[..2] == *b"\x16\x03" {
+            let mut server_config = match self.ca.gen_server_config(&authority).await {
+                Ok(server_config) => server_config,
+                Err(e) => {
+                    recorder.add_error(format!("Failed to build server config: {e}"));
+                    return;
+                }
+            };
+
+            // Advertise HTTP/2 and HTTP/1.1 over ALPN so the downstream client
+            // can negotiate h2 against the MITM server, matching the upstream.
+            // `make_mut` clones a shared/cached config rather than silently
+            // skipping the assignment when the `Arc` is not uniquely held.
+            Arc::make_mut(&mut server_config).alpn_protocols =
+                vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+            let stream = match TlsAcceptor::from(server_config).accept(upgraded).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    recorder.add_error(format!("Failed to establish TLS Connection: {e}"));
+                    return;
+                }
+            };
+
+            if let Err(e) = self
+                .clone()
+                .serve_connect_stream(stream, Scheme::HTTPS, authority, client_addr)
+                .await
+            {
+                if !e.to_string().starts_with("error shutting down connection") {
+                    recorder.add_error(format!("HTTPS connect error: {e}"));
+                }
+            }
+        } else {
+            recorder.add_error(format!(
+                "Unknown protocol, read '{:02X?}' from upgraded connection",
+                &buffer[..bytes_read]
+            ));
+
+            let mut server = match TcpStream::connect(authority.as_str()).await {
+                Ok(server) => server,
+                Err(e) => {
+                    recorder.add_error(format! {"Failed to connect to {authority}: {e}"});
+                    return;
+                }
+            };
+
+            if self.send_proxy_header {
+                if let Some(header) = &proxy_header {
+                    if let Err(e) = server.write_all(&header.raw).await {
+                        recorder.add_error(format!("Failed to send PROXY header upstream: {e}"));
+                        return;
+                    }
+                }
+            }
+
+            if let Err(e) = tokio::io::copy_bidirectional(&mut upgraded, &mut server).await {
+                recorder.add_error(format!(
+                    "Failed to tunnel unknown protocol to {}: {}",
+                    authority, e
+                ));
+            }
+        }
+    }
+
     async fn serve_connect_stream<I>(
         self: Arc<Self>,
         stream: I,
         scheme: Scheme,
         authority: Authority,
+        client_addr: Option<Arc<str>>,
     ) -> Result<(), Box<dyn std::error::Error + Sync + Send>>
     where
         I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
@@ -433,6 +683,12 @@ impl Server {
                 req = Request::from_parts(parts, body);
             };
 
+            if let Some(client) = &client_addr {
+                if let Ok(value) = HeaderValue::from_str(client) {
+                    req.headers_mut().insert("proxyfor-client-address", value);
+                }
+            }
+
             self.clone().handle(req)
         });
 
@@ -452,12 +708,576 @@ impl Server {
         error: T,
         mut recorder: Recorder,
     ) {
+        self.metrics.on_upstream_error();
         recorder.add_error(error.to_string());
         self.take_recorder(recorder);
         *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
     }
 }
 
+/// Parsed query parameters for the `/__proxyfor__/traffics` REST API.
+#[derive(Default)]
+struct TrafficQuery {
+    method: Option<String>,
+    status: Option<String>,
+    mime: Option<String>,
+    host: Option<String>,
+    q: Option<String>,
+    sort: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl TrafficQuery {
+    fn parse(query: &str) -> Self {
+        let mut params = TrafficQuery::default();
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            let value = value.into_owned();
+            if value.is_empty() {
+                continue;
+            }
+            match key.as_ref() {
+                "method" => params.method = Some(value),
+                "status" => params.status = Some(value),
+                "mime" => params.mime = Some(value),
+                "host" => params.host = Some(value),
+                "q" => params.q = Some(value),
+                "sort" => params.sort = Some(value),
+                "limit" => params.limit = value.parse().ok(),
+                "offset" => params.offset = value.parse().ok(),
+                _ => {}
+            }
+        }
+        params
+    }
+
+    fn matches(&self, item: &serde_json::Value) -> bool {
+        if let Some(method) = &self.method {
+            if !field_str(item, "method")
+                .map(|v| v.eq_ignore_ascii_case(method))
+                .unwrap_or(false)
+            {
+                return false;
+            }
+        }
+        if let Some(status) = &self.status {
+            if field_str(item, "status").as_deref() != Some(status.as_str()) {
+                return false;
+            }
+        }
+        if let Some(mime) = &self.mime {
+            let content_type = field_str(item, "mime").unwrap_or_default();
+            if !is_match_type(std::slice::from_ref(mime), &content_type) {
+                return false;
+            }
+        }
+        if let Some(host) = &self.host {
+            // Match only the host component of the request URI, not the path.
+            let matched = field_str(item, "uri")
+                .and_then(|uri| uri.parse::<Uri>().ok())
+                .and_then(|uri| uri.host().map(|h| h.to_string()))
+                .map(|h| h.contains(host.as_str()))
+                .unwrap_or(false);
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(q) = &self.q {
+            // `q` is a substring match against the traffic title (its URI).
+            let title = field_str(item, "uri").unwrap_or_default();
+            if !title.contains(q.as_str()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Render a JSON field as a plain string, flattening scalar numbers/strings.
+fn field_str(item: &serde_json::Value, key: &str) -> Option<String> {
+    match item.get(key)? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Atomic counters exposed in Prometheus text exposition format.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    requests_received: AtomicU64,
+    requests_served: AtomicU64,
+    upstream_errors: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    requests_by_method: Mutex<BTreeMap<String, u64>>,
+    requests_by_status: Mutex<BTreeMap<u16, u64>>,
+}
+
+impl Metrics {
+    fn on_request_received(&self, method: &str) {
+        self.requests_received.fetch_add(1, Ordering::Relaxed);
+        bump(&self.requests_by_method, method.to_string());
+    }
+
+    fn on_request_served(&self) {
+        self.requests_served.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_upstream_error(&self) {
+        self.upstream_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_response(&self, status: StatusCode) {
+        bump(&self.requests_by_status, status.as_u16());
+    }
+
+    fn add_bytes_in(&self, n: u64) {
+        self.bytes_in.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn add_bytes_out(&self, n: u64) {
+        self.bytes_out.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Render all counters as Prometheus `# HELP`/`# TYPE`/value lines.
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let counter = |out: &mut String, name: &str, help: &str, value: u64| {
+            out.push_str(&format!("# HELP {name} {help}\n"));
+            out.push_str(&format!("# TYPE {name} counter\n"));
+            out.push_str(&format!("{name} {value}\n"));
+        };
+
+        counter(
+            &mut out,
+            "proxyfor_requests_received_total",
+            "Total requests received from downstream clients.",
+            self.requests_received.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "proxyfor_requests_served_total",
+            "Total requests whose upstream response head was returned.",
+            self.requests_served.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "proxyfor_upstream_errors_total",
+            "Total upstream request failures.",
+            self.upstream_errors.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "proxyfor_bytes_in_total",
+            "Total request body bytes received.",
+            self.bytes_in.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "proxyfor_bytes_out_total",
+            "Total response body bytes streamed to clients.",
+            self.bytes_out.load(Ordering::Relaxed),
+        );
+
+        let render_labeled = |out: &mut String, name: &str, help: &str, label: &str, map: &Mutex<BTreeMap<String, u64>>| {
+            out.push_str(&format!("# HELP {name} {help}\n"));
+            out.push_str(&format!("# TYPE {name} counter\n"));
+            for (key, value) in map.lock().unwrap().iter() {
+                out.push_str(&format!("{name}{{{label}=\"{}\"}} {value}\n", escape_label(key)));
+            }
+        };
+
+        render_labeled(
+            &mut out,
+            "proxyfor_requests_by_method_total",
+            "Total requests by HTTP method.",
+            "method",
+            &self.requests_by_method,
+        );
+
+        out.push_str("# HELP proxyfor_requests_by_status_total Total responses by status code.\n");
+        out.push_str("# TYPE proxyfor_requests_by_status_total counter\n");
+        for (status, value) in self.requests_by_status.lock().unwrap().iter() {
+            out.push_str(&format!(
+                "proxyfor_requests_by_status_total{{status=\"{status}\"}} {value}\n"
+            ));
+        }
+
+        out
+    }
+}
+
+fn bump<K: Ord>(map: &Mutex<BTreeMap<K, u64>>, key: K) {
+    *map.lock().unwrap().entry(key).or_insert(0) += 1;
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A parsed PROXY-protocol header recovered from a downstream connection.
+struct ProxyHeader {
+    /// Source address (`ip:port`) of the real client, when present.
+    client: Option<String>,
+    /// The exact header bytes, so they can be replayed to an upstream verbatim.
+    raw: Vec<u8>,
+}
+
+/// Read and parse a PROXY-protocol header from the front of `stream`.
+///
+/// `is_v2` selects the binary v2 layout (12-byte signature) versus the v1 ASCII
+/// line (`PROXY TCP4 <src> <dst> <sport> <dport>\r\n`).
+async fn read_proxy_header<I: AsyncRead + Unpin>(
+    stream: &mut I,
+    is_v2: bool,
+) -> Result<ProxyHeader> {
+    if !is_v2 {
+        // v1: read the CRLF-terminated ASCII line one byte at a time.
+        let mut raw = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            stream.read_exact(&mut byte).await?;
+            raw.push(byte[0]);
+            if raw.ends_with(b"\r\n") {
+                break;
+            }
+            if raw.len() > 107 {
+                return Err(anyhow!("PROXY v1 header too long"));
+            }
+        }
+        let line = std::str::from_utf8(&raw)?.trim_end();
+        let fields: Vec<&str> = line.split(' ').collect();
+        // PROXY TCP4 srcip dstip srcport dstport
+        let client = match fields.as_slice() {
+            [_, proto, src, _dst, sport, _dport] if *proto == "TCP4" || *proto == "TCP6" => {
+                Some(format!("{src}:{sport}"))
+            }
+            _ => None,
+        };
+        return Ok(ProxyHeader { client, raw });
+    }
+
+    // v2: 12-byte signature + version/command + family + 2-byte length. The
+    // sniffed signature bytes were rewound into the stream, so read the whole
+    // 16-byte header back rather than reconstructing the prefix by hand.
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header).await?;
+    if &header[..12] != b"\x0D\x0A\x0D\x0A\x00\x0D\x0A\x51\x55\x49\x54\x0A" {
+        return Err(anyhow!("invalid PROXY v2 signature"));
+    }
+    let family = header[13];
+    let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+    let mut addr = vec![0u8; len];
+    stream.read_exact(&mut addr).await?;
+
+    let mut raw = header.to_vec();
+    raw.extend_from_slice(&addr);
+
+    // High nibble of the family byte: 0x1 = AF_INET, 0x2 = AF_INET6.
+    let client = match family >> 4 {
+        0x1 if addr.len() >= 12 => {
+            let ip = std::net::Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]);
+            let port = u16::from_be_bytes([addr[8], addr[9]]);
+            Some(format!("{ip}:{port}"))
+        }
+        0x2 if addr.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addr[0..16]);
+            let ip = std::net::Ipv6Addr::from(octets);
+            let port = u16::from_be_bytes([addr[32], addr[33]]);
+            Some(format!("[{ip}]:{port}"))
+        }
+        _ => None,
+    };
+    Ok(ProxyHeader { client, raw })
+}
+
+/// Direction of a recorded WebSocket message relative to the proxied client.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebSocketDirection {
+    ClientToServer,
+    ServerToClient,
+}
+
+/// A reassembled WebSocket message captured off an upgraded tunnel.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct WebSocketMessage {
+    pub(crate) direction: WebSocketDirection,
+    /// RFC 6455 opcode: 0x1 text, 0x2 binary, 0x8 close, 0x9 ping, 0xA pong.
+    pub(crate) opcode: u8,
+    pub(crate) payload: Bytes,
+}
+
+/// Surface a decoded WebSocket message through the existing traffic stream so
+/// it shows up in `handle_list_traffis`, `handle_traffic_info`, and `/subscribe`
+/// alongside ordinary requests.
+fn record_ws_message(state: &State, message: WebSocketMessage) {
+    let arrow = match message.direction {
+        WebSocketDirection::ClientToServer => "\u{2192}", // →
+        WebSocketDirection::ServerToClient => "\u{2190}", // ←
+    };
+    let kind = match message.opcode {
+        0x1 => "text",
+        0x2 => "binary",
+        0x8 => "close",
+        0x9 => "ping",
+        0xA => "pong",
+        _ => "continuation",
+    };
+    let mut recorder = Recorder::new(&format!("ws {arrow} {kind}"), "WEBSOCKET");
+    recorder.set_res_body(message.payload);
+    state.add_trafic(recorder.take_traffic());
+}
+
+/// Incremental RFC 6455 frame decoder for a single direction.
+///
+/// Bytes are pushed in as they arrive; complete messages (continuation frames
+/// reassembled up to the FIN bit) are appended to `out` in the order they finish.
+#[derive(Default)]
+struct WsDecoder {
+    buffer: Vec<u8>,
+    message: Vec<u8>,
+    message_opcode: Option<u8>,
+}
+
+impl WsDecoder {
+    fn push(&mut self, data: &[u8], out: &mut Vec<(u8, Bytes)>) {
+        self.buffer.extend_from_slice(data);
+        while let Some((fin, opcode, payload, consumed)) = self.parse_frame() {
+            self.buffer.drain(..consumed);
+            // Control frames (0x8 close, 0x9 ping, 0xA pong) are always complete
+            // and may be interleaved between the fragments of a data message, so
+            // emit them standalone without disturbing the reassembly buffer.
+            if opcode >= 0x8 {
+                out.push((opcode, Bytes::from(payload)));
+                continue;
+            }
+            match opcode {
+                // Continuation frame: append to the message in progress.
+                0x0 => self.message.extend_from_slice(&payload),
+                // A new data frame (text/binary) begins a message.
+                _ => {
+                    self.message_opcode = Some(opcode);
+                    self.message.extend_from_slice(&payload);
+                }
+            }
+            if fin {
+                let opcode = self.message_opcode.take().unwrap_or(opcode);
+                let payload = Bytes::from(std::mem::take(&mut self.message));
+                out.push((opcode, payload));
+            }
+        }
+    }
+
+    /// Try to parse a single frame off the front of the buffer, returning
+    /// `(fin, opcode, unmasked_payload, bytes_consumed)` when a whole frame is
+    /// available.
+    fn parse_frame(&self) -> Option<(bool, u8, Vec<u8>, usize)> {
+        let buf = &self.buffer;
+        if buf.len() < 2 {
+            return None;
+        }
+        let fin = buf[0] & 0x80 != 0;
+        let opcode = buf[0] & 0x0f;
+        let masked = buf[1] & 0x80 != 0;
+        let len7 = (buf[1] & 0x7f) as usize;
+
+        let mut offset = 2;
+        let payload_len = match len7 {
+            126 => {
+                if buf.len() < offset + 2 {
+                    return None;
+                }
+                let len = u16::from_be_bytes([buf[offset], buf[offset + 1]]) as usize;
+                offset += 2;
+                len
+            }
+            127 => {
+                if buf.len() < offset + 8 {
+                    return None;
+                }
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&buf[offset..offset + 8]);
+                offset += 8;
+                u64::from_be_bytes(bytes) as usize
+            }
+            len => len,
+        };
+
+        let mask_key = if masked {
+            if buf.len() < offset + 4 {
+                return None;
+            }
+            let key = [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]];
+            offset += 4;
+            Some(key)
+        } else {
+            None
+        };
+
+        // Guard against a crafted 64-bit length overflowing `usize` and wrapping
+        // past the bounds check into an out-of-range slice panic.
+        let end = offset.checked_add(payload_len)?;
+        if buf.len() < end {
+            return None;
+        }
+
+        let mut payload = buf[offset..end].to_vec();
+        if let Some(key) = mask_key {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= key[i % 4];
+            }
+        }
+        Some((fin, opcode, payload, end))
+    }
+}
+
+/// A stream wrapper that taps an upgraded WebSocket tunnel, decoding frames in
+/// both directions and recording the reassembled messages into [`State`].
+///
+/// The HTTP upgrade handshake is skipped (up to the first `\r\n\r\n` seen in
+/// each direction) before frame decoding starts.
+struct WsTapStream<I> {
+    inner: I,
+    state: State,
+    read_decoder: WsDirectionTap,
+    write_decoder: WsDirectionTap,
+}
+
+struct WsDirectionTap {
+    direction: WebSocketDirection,
+    decoder: WsDecoder,
+    handshake_done: bool,
+    handshake_buf: Vec<u8>,
+}
+
+impl WsDirectionTap {
+    fn new(direction: WebSocketDirection) -> Self {
+        Self {
+            direction,
+            decoder: WsDecoder::default(),
+            handshake_done: false,
+            handshake_buf: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, data: &[u8], state: &State) {
+        if !self.handshake_done {
+            self.handshake_buf.extend_from_slice(data);
+            let Some(pos) = find_subsequence(&self.handshake_buf, b"\r\n\r\n") else {
+                return;
+            };
+            let rest = self.handshake_buf.split_off(pos + 4);
+            self.handshake_buf = Vec::new();
+            self.handshake_done = true;
+            self.decode_and_record(&rest, state);
+            return;
+        }
+        self.decode_and_record(data, state);
+    }
+
+    fn decode_and_record(&mut self, data: &[u8], state: &State) {
+        let mut out = Vec::new();
+        self.decoder.push(data, &mut out);
+        for (opcode, payload) in out {
+            record_ws_message(
+                state,
+                WebSocketMessage {
+                    direction: self.direction,
+                    opcode,
+                    payload,
+                },
+            );
+        }
+    }
+}
+
+impl<I> WsTapStream<I> {
+    fn new(inner: I, state: State) -> Self {
+        Self {
+            inner,
+            state,
+            read_decoder: WsDirectionTap::new(WebSocketDirection::ClientToServer),
+            write_decoder: WsDirectionTap::new(WebSocketDirection::ServerToClient),
+        }
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for WsTapStream<I> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let this = &mut *self;
+        let poll = std::pin::Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let std::task::Poll::Ready(Ok(())) = &poll {
+            let filled = buf.filled();
+            if filled.len() > before {
+                let data = filled[before..].to_vec();
+                this.read_decoder.feed(&data, &this.state);
+            }
+        }
+        poll
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for WsTapStream<I> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let poll = std::pin::Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let std::task::Poll::Ready(Ok(n)) = &poll {
+            let n = *n;
+            this.write_decoder.feed(&buf[..n], &this.state);
+        }
+        poll
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Drain an unbounded channel of body chunks into a single buffer, keeping at
+/// most `cap` bytes. Resolves when the sending half is dropped (stream EOF).
+async fn collect_capped(
+    rx: &mut tokio::sync::mpsc::UnboundedReceiver<Bytes>,
+    cap: usize,
+) -> Vec<u8> {
+    let mut body = Vec::new();
+    while let Some(chunk) = rx.recv().await {
+        if body.len() >= cap {
+            continue;
+        }
+        let take = (cap - body.len()).min(chunk.len());
+        body.extend_from_slice(&chunk[..take]);
+    }
+    body
+}
+
 fn set_res_body(res: &mut Response, body: String) {
     let body = Bytes::from(body);
     if let Ok(header_value) = HeaderValue::from_str(&body.len().to_string()) {
@@ -474,11 +1294,97 @@ fn subscribe_json_frame<T: Serialize>(head: &T) -> Frame<Bytes> {
     Frame::data(Bytes::from(data))
 }
 
+/// A content codec supported for outbound compression, ordered by preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Br,
+    Zstd,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Br => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "br" => Some(Encoding::Br),
+            "zstd" => Some(Encoding::Zstd),
+            "gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Server preference rank; lower is more preferred.
+    fn rank(&self) -> u8 {
+        match self {
+            Encoding::Br => 0,
+            Encoding::Zstd => 1,
+            Encoding::Gzip => 2,
+            Encoding::Deflate => 3,
+        }
+    }
+}
+
+/// Pick the best supported codec from a quality-valued `Accept-Encoding` list.
+///
+/// Tokens with `q=0` are rejected; otherwise the server's own preference order
+/// (br > zstd > gzip > deflate) breaks ties between equally-weighted codecs.
+fn select_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+    for part in accept_encoding.split(',') {
+        let mut fields = part.split(';');
+        let token = fields.next().unwrap_or("").trim();
+        let mut quality = 1.0f32;
+        for param in fields {
+            let param = param.trim();
+            if let Some(q) = param.strip_prefix("q=") {
+                quality = q.parse().unwrap_or(0.0);
+            }
+        }
+        if quality <= 0.0 {
+            continue;
+        }
+        let Some(encoding) = Encoding::from_token(token) else {
+            continue;
+        };
+        let better = match &best {
+            None => true,
+            Some((current, current_q)) => {
+                quality > *current_q
+                    || (quality == *current_q && encoding.rank() < current.rank())
+            }
+        };
+        if better {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+async fn compress(data: &[u8], encoding: Encoding) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::Deflate => compress_deflate(data).await,
+        Encoding::Gzip => compress_gzip(data).await,
+        Encoding::Br => compress_br(data).await,
+        Encoding::Zstd => compress_zstd(data).await,
+    }
+}
+
 async fn decompress(data: &Bytes, encoding: &str) -> Option<Vec<u8>> {
     match encoding {
         "deflate" => decompress_deflate(data).await.ok(),
         "gzip" => decompress_gzip(data).await.ok(),
         "br" => decompress_br(data).await.ok(),
+        "zstd" => decompress_zstd(data).await.ok(),
         _ => None,
     }
 }
@@ -497,3 +1403,138 @@ macro_rules! decompress_fn {
 decompress_fn!(decompress_deflate, DeflateDecoder);
 decompress_fn!(decompress_gzip, GzipDecoder);
 decompress_fn!(decompress_br, BrotliDecoder);
+decompress_fn!(decompress_zstd, ZstdDecoder);
+
+macro_rules! compress_fn {
+    ($fn_name:ident, $encoder:ident) => {
+        async fn $fn_name(in_data: &[u8]) -> Result<Vec<u8>> {
+            let mut encoder = $encoder::new(Vec::new());
+            encoder.write_all(in_data).await?;
+            encoder.shutdown().await?;
+            Ok(encoder.into_inner())
+        }
+    };
+}
+
+compress_fn!(compress_deflate, DeflateEncoder);
+compress_fn!(compress_gzip, GzipEncoder);
+compress_fn!(compress_br, BrotliEncoder);
+compress_fn!(compress_zstd, ZstdEncoder);
+
+#[cfg(test)]
+mod websocket_tests {
+    use super::*;
+
+    fn frame(fin: bool, opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.push(if fin { 0x80 } else { 0x00 } | opcode);
+        let mask_bit = if mask.is_some() { 0x80 } else { 0x00 };
+        let len = payload.len();
+        if len < 126 {
+            out.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        match mask {
+            Some(key) => {
+                out.extend_from_slice(&key);
+                for (i, byte) in payload.iter().enumerate() {
+                    out.push(byte ^ key[i % 4]);
+                }
+            }
+            None => out.extend_from_slice(payload),
+        }
+        out
+    }
+
+    #[test]
+    fn reassembles_masked_fragmented_text() {
+        let key = [0x11, 0x22, 0x33, 0x44];
+        let mut data = frame(false, 0x1, b"He", Some(key));
+        data.extend(frame(true, 0x0, b"llo", Some(key)));
+
+        let mut decoder = WsDecoder::default();
+        let mut out = Vec::new();
+        decoder.push(&data, &mut out);
+
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].0, 0x1);
+        assert_eq!(&out[0].1[..], b"Hello");
+    }
+
+    #[test]
+    fn control_frame_interleaved_mid_message() {
+        let key = [0x01, 0x02, 0x03, 0x04];
+        let mut data = frame(false, 0x1, b"He", Some(key));
+        data.extend(frame(true, 0x9, b"ping", Some(key)));
+        data.extend(frame(true, 0x0, b"llo", Some(key)));
+
+        let mut decoder = WsDecoder::default();
+        let mut out = Vec::new();
+        decoder.push(&data, &mut out);
+
+        assert_eq!(out.len(), 2);
+        // The ping surfaces standalone before the reassembled text message.
+        assert_eq!(out[0].0, 0x9);
+        assert_eq!(&out[0].1[..], b"ping");
+        assert_eq!(out[1].0, 0x1);
+        assert_eq!(&out[1].1[..], b"Hello");
+    }
+
+    #[test]
+    fn oversized_length_does_not_panic() {
+        // FIN + binary, unmasked, 64-bit length of u64::MAX with no payload.
+        let mut data = vec![0x82, 127];
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+
+        let mut decoder = WsDecoder::default();
+        let mut out = Vec::new();
+        decoder.push(&data, &mut out);
+
+        assert!(out.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod proxy_header_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn parses_v1_tcp4() {
+        let raw = b"PROXY TCP4 1.2.3.4 5.6.7.8 1111 443\r\n";
+        let mut stream: &[u8] = raw;
+        let header = read_proxy_header(&mut stream, false).await.unwrap();
+        assert_eq!(header.client.as_deref(), Some("1.2.3.4:1111"));
+        assert_eq!(header.raw, raw.to_vec());
+    }
+
+    #[tokio::test]
+    async fn parses_v1_unknown() {
+        let raw = b"PROXY UNKNOWN\r\n";
+        let mut stream: &[u8] = raw;
+        let header = read_proxy_header(&mut stream, false).await.unwrap();
+        assert_eq!(header.client, None);
+    }
+
+    #[tokio::test]
+    async fn parses_v2_inet() {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(b"\x0D\x0A\x0D\x0A\x00\x0D\x0A\x51\x55\x49\x54\x0A"); // signature
+        raw.push(0x21); // version 2, PROXY command
+        raw.push(0x11); // AF_INET + STREAM
+        raw.extend_from_slice(&12u16.to_be_bytes()); // address block length
+        raw.extend_from_slice(&[1, 2, 3, 4]); // source address
+        raw.extend_from_slice(&[5, 6, 7, 8]); // destination address
+        raw.extend_from_slice(&8080u16.to_be_bytes()); // source port
+        raw.extend_from_slice(&443u16.to_be_bytes()); // destination port
+
+        let mut stream: &[u8] = &raw;
+        let header = read_proxy_header(&mut stream, true).await.unwrap();
+        assert_eq!(header.client.as_deref(), Some("1.2.3.4:8080"));
+        assert_eq!(header.raw, raw);
+    }
+}